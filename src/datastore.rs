@@ -1,14 +1,16 @@
 use csv::StringRecord;
+use futures::stream::{self, StreamExt};
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
-use serenity::model::prelude::{GuildId, Message, UserId};
+use serenity::model::prelude::{GuildId, Message, User, UserId};
 use serenity::model::Timestamp;
 use serenity::prelude::*;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
+    env,
     fs::{self, File},
     io,
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 use time::macros::format_description;
 
@@ -18,12 +20,89 @@ pub struct WebhookData {
     msg_counts: HashMap<u16, u32>,
 }
 
+// A single message record as produced by an offline export tool. Only the
+// fields `process_message` actually needs are required; `author_name` is
+// optional since many dumps only record the author's numeric ID. `is_bot`,
+// `is_webhook` and `author_avatar_url` default to false/absent so dumps from
+// exporters that don't carry them still import, just without that filtering.
+#[derive(Deserialize)]
+struct ArchiveMessage {
+    id: u64,
+    channel_id: u64,
+    author_id: u64,
+    #[serde(default)]
+    author_name: Option<String>,
+    timestamp: i64,
+    #[serde(default)]
+    content: Option<String>,
+    #[serde(default)]
+    is_bot: bool,
+    #[serde(default)]
+    is_webhook: bool,
+    #[serde(default)]
+    author_avatar_url: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Default)]
 pub struct Datastore {
     range: (Option<u16>, Option<u16>),
     user_data: HashMap<u64, HashMap<u16, u32>>,
     wh_data: HashMap<String /* username */, WebhookData>,
     pub last_fetches: HashMap<u64, i64>,
+    // Display names recovered from archive imports for users the live API
+    // can no longer resolve (e.g. deleted accounts).
+    //
+    // `serde(default)` lets caches written before this field existed keep
+    // loading instead of failing deserialization and silently reverting to
+    // `Datastore::default()`.
+    #[serde(default)]
+    known_names: HashMap<u64, String>,
+    // Opt-in (see `ANALYZE_CONTENT`) per-user top term frequencies, pruned to
+    // `TOP_WORDS_PER_USER` on save. `serde(default)` keeps caches written
+    // before this field existed loadable instead of failing deserialization.
+    #[serde(default)]
+    word_data: HashMap<u64, HashMap<String, u32>>,
+    // Opt-in per-user message tally by channel. Same backward-compatibility
+    // concern as `word_data` above.
+    #[serde(default)]
+    channel_data: HashMap<u64, HashMap<u64, u32>>,
+}
+
+// The fixed UTC offset used to bucket messages into days, read once from
+// `UMS_TIMEZONE` (or `LOCAL_TIMEZONE` as a fallback). Defaults to UTC so
+// existing deployments are unaffected.
+static LOCAL_OFFSET: Lazy<time::UtcOffset> = Lazy::new(|| {
+    let raw = env::var("UMS_TIMEZONE")
+        .or_else(|_| env::var("LOCAL_TIMEZONE"))
+        .unwrap_or_else(|_| "+00:00".into());
+
+    parse_utc_offset(&raw)
+        .unwrap_or_else(|e| panic!("Invalid UMS_TIMEZONE/LOCAL_TIMEZONE {raw:?}: {e}"))
+});
+
+// Parses a fixed offset of the form `[+-]HH[:MM]`, e.g. `+05:30` or `-4`.
+fn parse_utc_offset(raw: &str) -> Result<time::UtcOffset, String> {
+    let raw = raw.trim();
+    let (sign, rest): (i8, &str) = match raw.strip_prefix('-') {
+        Some(r) => (-1, r),
+        None => (1, raw.strip_prefix('+').unwrap_or(raw)),
+    };
+
+    let mut parts = rest.splitn(2, ':');
+    let hours: i8 = parts
+        .next()
+        .filter(|s| !s.is_empty())
+        .ok_or_else(|| "missing hour component".to_string())?
+        .parse()
+        .map_err(|_| "hour component must be an integer".to_string())?;
+    let minutes: i8 = match parts.next() {
+        Some(m) => m
+            .parse()
+            .map_err(|_| "minute component must be an integer".to_string())?,
+        None => 0,
+    };
+
+    time::UtcOffset::from_hms(sign * hours, sign * minutes, 0).map_err(|e| e.to_string())
 }
 
 static CACHE_DIR: Lazy<PathBuf> = Lazy::new(|| {
@@ -52,6 +131,73 @@ static DATA_DIR: Lazy<PathBuf> = Lazy::new(|| {
 
 const DEFAULT_PFP: &str = "https://cdn.discordapp.com/embed/avatars/0.png";
 
+// How many cache-miss users to resolve concurrently at once in `resolve_users`.
+const USER_RESOLVE_CHUNK_SIZE: usize = 50;
+
+// Whether to accumulate per-user word frequencies and per-channel tallies
+// while processing messages. Off by default since it roughly doubles the
+// bookkeeping work done per message.
+static ANALYZE_CONTENT: Lazy<bool> = Lazy::new(|| env_flag("UMS_ANALYZE_CONTENT"));
+
+// Drop messages from bot accounts before they ever reach `user_data`.
+static IGNORE_BOTS: Lazy<bool> = Lazy::new(|| env_flag("IGNORE_BOTS"));
+
+// Drop webhook/NQN messages (the `wh_data` branch) entirely.
+static IGNORE_WEBHOOKS: Lazy<bool> = Lazy::new(|| env_flag("IGNORE_WEBHOOKS"));
+
+// Reads a boolean toggle from the environment: "1" or "true" (case-insensitive)
+// is on, anything else (including unset) is off.
+fn env_flag(key: &str) -> bool {
+    env::var(key)
+        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+// How many of a user's most-used terms to retain once `word_data` is pruned.
+const TOP_WORDS_PER_USER: usize = 100;
+
+static STOP_WORDS: Lazy<HashSet<&'static str>> = Lazy::new(|| {
+    [
+        "a", "an", "the", "and", "or", "but", "if", "so", "to", "of", "in", "on", "at", "for",
+        "with", "is", "it", "its", "be", "been", "being", "as", "this", "that", "these", "those",
+        "i", "you", "he", "she", "we", "they", "my", "your", "his", "her", "our", "their", "are",
+        "was", "were", "do", "does", "did", "not", "no", "yes", "up", "out", "just", "like",
+    ]
+    .into_iter()
+    .collect()
+});
+
+// Lowercases, strips punctuation, and drops stop words from a message body.
+fn tokenize(content: &str) -> impl Iterator<Item = String> + '_ {
+    content
+        .split_whitespace()
+        .map(|w| {
+            w.chars()
+                .filter(|c| c.is_alphanumeric())
+                .collect::<String>()
+                .to_lowercase()
+        })
+        .filter(|w| !w.is_empty() && !STOP_WORDS.contains(w.as_str()))
+}
+
+// The subset of a resolved `User` that `generate_user_header` needs, cached up
+// front so `write_out` does not await one HTTP request per row.
+struct ResolvedUser {
+    tag: String,
+    pfp: String,
+    bot: bool,
+}
+
+impl From<&User> for ResolvedUser {
+    fn from(u: &User) -> Self {
+        ResolvedUser {
+            tag: u.tag(),
+            pfp: u.avatar_url().unwrap_or(DEFAULT_PFP.into()),
+            bot: u.bot,
+        }
+    }
+}
+
 impl Datastore {
     // Attempts to load from the cache if it exists.
     pub fn load_from_cache(guild_id: &GuildId) -> Option<Self> {
@@ -66,11 +212,13 @@ impl Datastore {
     }
 
     // Save the contents of this Datastore to the cache for future runs.
-    pub fn save_to_cache(&self, guild_id: &GuildId) -> io::Result<()> {
+    pub fn save_to_cache(&mut self, guild_id: &GuildId) -> io::Result<()> {
+        self.prune_word_data();
+
         let ds_file = CACHE_DIR.join(format!("ds_{guild_id}.cbor"));
 
         let fd = File::create(ds_file)?;
-        if let Err(e) = ciborium::ser::into_writer(&self, fd) {
+        if let Err(e) = ciborium::ser::into_writer(&*self, fd) {
             use ciborium::ser::Error::*;
             match e {
                 Io(err) => return Err(err),
@@ -83,100 +231,334 @@ impl Datastore {
 
     // Processes a single message, assumed to be new, and updates the datastore using it.
     pub fn process_message(&mut self, msg: &Message) {
-        let uday = timestamp_to_uday(&msg.timestamp);
+        if msg.author.discriminator != 0 {
+            let uday = timestamp_to_uday(&msg.timestamp);
+            self.process_user_entry(
+                msg.author.id.0,
+                msg.author.bot,
+                uday,
+                msg.channel_id.0,
+                Some(&msg.content),
+            );
+        } else {
+            let uday = timestamp_to_uday(&msg.timestamp);
+            self.process_webhook_entry(&msg.author.name, msg.author.avatar_url(), uday);
+        }
+    }
 
-        // Update the range to include this uday if it does not already.
-        use std::cmp::{max, min};
-        self.range.0 = Some(min(self.range.0.unwrap_or(u16::MAX), uday));
-        self.range.1 = Some(max(self.range.1.unwrap_or(u16::MIN), uday));
+    // Shared by `process_message` and `import_archive_file` so live and
+    // imported traffic are filtered identically. `IGNORE_BOTS` must not
+    // affect the webhook path below, since the two toggles are independent.
+    fn process_user_entry(
+        &mut self,
+        author_id: u64,
+        is_bot: bool,
+        uday: u16,
+        channel_id: u64,
+        content: Option<&str>,
+    ) {
+        if *IGNORE_BOTS && is_bot {
+            return;
+        }
+
+        self.tally_user(author_id, uday);
+
+        if *ANALYZE_CONTENT {
+            self.tally_channel(author_id, channel_id);
+            if let Some(content) = content {
+                self.tally_words(author_id, content);
+            }
+        }
+    }
+
+    // Counterpart to `process_user_entry` for webhook-authored traffic.
+    fn process_webhook_entry(&mut self, name: &str, avatar_url: Option<String>, uday: u16) {
+        if *IGNORE_WEBHOOKS {
+            return;
+        }
+
+        self.tally_webhook(name, avatar_url, uday);
+    }
 
-        // Get the entry for this user in the user_data hash table.
-        let user_entry = if msg.author.discriminator != 0 {
-            // For regular users or bots.
-            let user_id = msg.author.id.0;
-            match self.user_data.get_mut(&user_id) {
-                Some(hm) => hm,
-                None => {
-                    self.user_data.insert(user_id, HashMap::default());
-                    self.user_data.get_mut(&user_id).unwrap()
+    // Imports a previously exported message dump, bypassing the live Discord API
+    // entirely. `path` may point at a single archive file or a folder, in which
+    // case every file in the folder is imported in turn. Each line of an archive
+    // file is a standalone JSON record (NDJSON) describing one message. `users_file`,
+    // if given, maps numeric author IDs to display names and is used to backfill
+    // any entry that does not carry its own `author_name`.
+    pub fn import_archive(&mut self, path: &Path, users_file: Option<&Path>) -> io::Result<()> {
+        let known_names = match users_file {
+            Some(p) => Self::load_users_file(p)?,
+            None => HashMap::default(),
+        };
+
+        if path.is_dir() {
+            for entry in fs::read_dir(path)? {
+                let entry_path = entry?.path();
+                if entry_path.is_file() {
+                    self.import_archive_file(&entry_path, &known_names)?;
                 }
             }
         } else {
-            // For messages sent using webhooks.
-            let name = &msg.author.name;
-            &mut match self.wh_data.get_mut(name) {
-                Some(hm) => hm,
-                None => {
-                    self.wh_data.insert(
-                        name.clone(),
-                        WebhookData {
-                            avatar_url: msg.author.avatar_url().unwrap_or(DEFAULT_PFP.into()),
-                            msg_counts: HashMap::default(),
-                        },
-                    );
-                    self.wh_data.get_mut(name).unwrap()
+            self.import_archive_file(path, &known_names)?;
+        }
+
+        Ok(())
+    }
+
+    fn load_users_file(path: &Path) -> io::Result<HashMap<u64, String>> {
+        let data = fs::read_to_string(path)?;
+        serde_json::from_str(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn import_archive_file(
+        &mut self,
+        path: &Path,
+        known_names: &HashMap<u64, String>,
+    ) -> io::Result<()> {
+        let data = fs::read_to_string(path)?;
+
+        // Snapshot the last-fetch cursors before processing any entry. The
+        // dump is not guaranteed to be ordered by ID, so comparing against a
+        // cursor that advances mid-loop could mistake an earlier, legitimate
+        // entry for a duplicate of one already seen later in the same file.
+        let baseline_fetches = self.last_fetches.clone();
+
+        for line in data.lines().filter(|l| !l.trim().is_empty()) {
+            let entry: ArchiveMessage = serde_json::from_str(line)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+            // Skip anything a live crawl of this channel had already seen
+            // before this import started.
+            let last_fetch = baseline_fetches
+                .get(&entry.channel_id)
+                .copied()
+                .unwrap_or(0);
+            if entry.id as i64 <= last_fetch {
+                continue;
+            }
+
+            let uday = unix_to_uday(entry.timestamp);
+            let resolved_name = entry
+                .author_name
+                .clone()
+                .or_else(|| known_names.get(&entry.author_id).cloned());
+
+            // `Message` carries private, Discord-gateway-only fields that
+            // can't be reconstructed from an archive dump, so we can't call
+            // `process_message` directly here. Instead both paths share
+            // `process_user_entry`/`process_webhook_entry`, which keeps
+            // `IGNORE_BOTS`/`IGNORE_WEBHOOKS` filtering and the user/webhook
+            // split identical for live and imported traffic.
+            if entry.is_webhook {
+                if let Some(name) = &resolved_name {
+                    self.process_webhook_entry(name, entry.author_avatar_url.clone(), uday);
+                }
+            } else {
+                self.process_user_entry(
+                    entry.author_id,
+                    entry.is_bot,
+                    uday,
+                    entry.channel_id,
+                    entry.content.as_deref(),
+                );
+
+                if let Some(name) = resolved_name {
+                    self.known_names.insert(entry.author_id, name);
                 }
             }
-            .msg_counts
+
+            let fetch_entry = self.last_fetches.entry(entry.channel_id).or_insert(0);
+            *fetch_entry = (*fetch_entry).max(entry.id as i64);
+        }
+
+        Ok(())
+    }
+
+    // Updates the overall day range to include `uday` if it does not already.
+    fn extend_range(&mut self, uday: u16) {
+        use std::cmp::{max, min};
+        self.range.0 = Some(min(self.range.0.unwrap_or(u16::MAX), uday));
+        self.range.1 = Some(max(self.range.1.unwrap_or(u16::MIN), uday));
+    }
+
+    // Records a single message from a regular user (or bot) against `user_data`.
+    fn tally_user(&mut self, user_id: u64, uday: u16) {
+        self.extend_range(uday);
+
+        let user_entry = match self.user_data.get_mut(&user_id) {
+            Some(hm) => hm,
+            None => {
+                self.user_data.insert(user_id, HashMap::default());
+                self.user_data.get_mut(&user_id).unwrap()
+            }
         };
 
-        // Update this user's entry.
         let curr_value = user_entry.get(&uday).unwrap_or(&0);
         user_entry.insert(uday, curr_value + 1);
     }
 
+    // Records a single message sent via a webhook/NQN against `wh_data`.
+    fn tally_webhook(&mut self, name: &str, avatar_url: Option<String>, uday: u16) {
+        self.extend_range(uday);
+
+        let user_entry = &mut match self.wh_data.get_mut(name) {
+            Some(hm) => hm,
+            None => {
+                self.wh_data.insert(
+                    name.into(),
+                    WebhookData {
+                        avatar_url: avatar_url.unwrap_or(DEFAULT_PFP.into()),
+                        msg_counts: HashMap::default(),
+                    },
+                );
+                self.wh_data.get_mut(name).unwrap()
+            }
+        }
+        .msg_counts;
+
+        let curr_value = user_entry.get(&uday).unwrap_or(&0);
+        user_entry.insert(uday, curr_value + 1);
+    }
+
+    // Records a single message against a user's per-channel activity tally.
+    fn tally_channel(&mut self, user_id: u64, channel_id: u64) {
+        let counts = self.channel_data.entry(user_id).or_default();
+        *counts.entry(channel_id).or_insert(0) += 1;
+    }
+
+    // Tokenizes a message body and records each term against a user's word
+    // frequency table.
+    fn tally_words(&mut self, user_id: u64, content: &str) {
+        let counts = self.word_data.entry(user_id).or_default();
+        for word in tokenize(content) {
+            *counts.entry(word).or_insert(0) += 1;
+        }
+    }
+
+    // Prunes every user's word frequency table down to its top
+    // `TOP_WORDS_PER_USER` terms so the CBOR cache cannot grow without bound.
+    fn prune_word_data(&mut self) {
+        for counts in self.word_data.values_mut() {
+            if counts.len() <= TOP_WORDS_PER_USER {
+                continue;
+            }
+
+            let mut top: Vec<(String, u32)> = counts.drain().collect();
+            top.sort_by(|a, b| b.1.cmp(&a.1));
+            top.truncate(TOP_WORDS_PER_USER);
+            *counts = top.into_iter().collect();
+        }
+    }
+
     // Write the contents of this datastore to a CSV in the desired format.
     // Returns the output file path on success.
     pub async fn write_out(
         &self,
         guild_id: &GuildId,
         con: &Context,
-    ) -> io::Result<(PathBuf, PathBuf)> {
-        let paths = (
-            DATA_DIR.join(format!("{guild_id}_daily.csv")),
-            DATA_DIR.join(format!("{guild_id}_totals.csv")),
-        );
-        let mut wtr_daily = csv::Writer::from_writer(File::create(&paths.0)?);
-        let mut wtr_totals = csv::Writer::from_writer(File::create(&paths.1)?);
-
+    ) -> io::Result<(Vec<PathBuf>, Option<PathBuf>, Option<PathBuf>)> {
         // Obtain the ranges.
         let low_bound = self.range.0.unwrap_or_default();
         let high_bound = self.range.1.unwrap_or_default();
+        let dates: Vec<String> = (low_bound..=high_bound).map(uday_to_date).collect();
 
-        // Write the header of the CSV.
-        let mut header = StringRecord::from(vec!["Username", "Category", "PFP"]);
-        for i in low_bound..=high_bound {
-            header.push_field(&uday_to_date(i));
-        }
-        wtr_daily.write_record(&header)?;
-        wtr_totals.write_record(&header)?;
+        // Resolve every distinct author up front so row assembly below is
+        // synchronous instead of awaiting one HTTP request per user.
+        let resolved_users = resolve_users(self.user_data.keys().copied(), con).await;
+
+        // Assemble every user/webhook into a format-agnostic row so any output
+        // backend below can consume the same data.
+        let mut rows = Vec::with_capacity(self.user_data.len() + self.wh_data.len());
 
-        // Write a row for each user.
         for (k, v) in self.user_data.iter() {
             let stats = MessageStats::generate(v, (low_bound, high_bound));
-            let row_header = &generate_user_header(UserId(*k), con, stats.total).await;
-
-            wtr_daily.write_record(&[row_header, &stats.daily[..]].concat())?;
-            wtr_totals.write_record(&[row_header, &stats.totals[..]].concat())?;
+            let resolved = resolved_users.get(k).and_then(Option::as_ref);
+            let header = generate_user_header(*k, resolved, stats.total, &self.known_names);
+            rows.push(StatsRow {
+                id: Some(*k),
+                header,
+                daily: stats.daily,
+                totals: stats.totals,
+            });
         }
 
-        // Write a row for each webhook.
         for (k, v) in self.wh_data.iter() {
             let stats = MessageStats::generate(&v.msg_counts, (low_bound, high_bound));
-            let row_header = &[format!("(NQN) {k}"), "NQN Webhooks".into(), v.avatar_url.clone()];
-
-            wtr_daily.write_record(&[row_header, &stats.daily[..]].concat())?;
-            wtr_totals.write_record(&[row_header, &stats.totals[..]].concat())?;
+            rows.push(StatsRow {
+                id: None,
+                header: [format!("(NQN) {k}"), "NQN Webhooks".into(), v.avatar_url.clone()],
+                daily: stats.daily,
+                totals: stats.totals,
+            });
         }
 
-        Ok(paths)
+        let stats_paths = match OutputFormat::from_env() {
+            OutputFormat::Csv => write_csv(guild_id, &dates, &rows)?,
+            OutputFormat::Json => write_json(guild_id, &dates, &rows)?,
+            OutputFormat::Sqlite => write_sqlite(guild_id, &dates, &rows)?,
+        };
+
+        // Write the per-user top term frequencies, if any were collected.
+        let words_path = if self.word_data.is_empty() {
+            None
+        } else {
+            let path = DATA_DIR.join(format!("{guild_id}_words.csv"));
+            let mut wtr = csv::Writer::from_writer(File::create(&path)?);
+            wtr.write_record(["Username", "Word", "Count"])?;
+
+            for (user_id, counts) in self.word_data.iter() {
+                let tag = resolve_tag(
+                    *user_id,
+                    resolved_users.get(user_id).and_then(Option::as_ref),
+                    &self.known_names,
+                );
+
+                let mut top: Vec<(&String, &u32)> = counts.iter().collect();
+                top.sort_by(|a, b| b.1.cmp(a.1));
+
+                for (word, count) in top {
+                    wtr.write_record([&tag, word, &count.to_string()])?;
+                }
+            }
+
+            Some(path)
+        };
+
+        // Write the per-user, per-channel activity tally, if any was collected.
+        let channels_path = if self.channel_data.is_empty() {
+            None
+        } else {
+            let path = DATA_DIR.join(format!("{guild_id}_channels.csv"));
+            let mut wtr = csv::Writer::from_writer(File::create(&path)?);
+            wtr.write_record(["Username", "ChannelID", "Count"])?;
+
+            for (user_id, counts) in self.channel_data.iter() {
+                let tag = resolve_tag(
+                    *user_id,
+                    resolved_users.get(user_id).and_then(Option::as_ref),
+                    &self.known_names,
+                );
+
+                let mut top: Vec<(&u64, &u32)> = counts.iter().collect();
+                top.sort_by(|a, b| b.1.cmp(a.1));
+
+                for (channel_id, count) in top {
+                    wtr.write_record([&tag, &channel_id.to_string(), &count.to_string()])?;
+                }
+            }
+
+            Some(path)
+        };
+
+        Ok((stats_paths, words_path, channels_path))
     }
 }
 
 struct MessageStats {
     total: u32,
-    daily: Vec<String>,
-    totals: Vec<String>
+    daily: Vec<u32>,
+    totals: Vec<u32>,
 }
 
 impl MessageStats {
@@ -185,42 +567,244 @@ impl MessageStats {
         let mut out = MessageStats {
             total: 0,
             daily: Vec::with_capacity(stats_len),
-            totals: Vec::with_capacity(stats_len)
+            totals: Vec::with_capacity(stats_len),
         };
 
         for i in (range.0)..=(range.1) {
             let entry = data.get(&i).unwrap_or(&0);
-            out.daily.push(entry.to_string());
-            out.totals.push((out.total + entry).to_string());
+            out.daily.push(*entry);
             out.total += entry;
+            out.totals.push(out.total);
         }
 
         out
     }
 }
 
-async fn generate_user_header(user_id: UserId, con: &Context, total: u32) -> [String; 3] {
-    let user = user_id.to_user(con).await.ok();
+// One user/webhook's computed message stats, ready for any output backend to
+// serialize without needing to know anything about `Datastore`'s internals.
+struct StatsRow {
+    // The Discord user ID, when this row is a regular user rather than a
+    // webhook (webhooks have no stable numeric ID).
+    id: Option<u64>,
+    header: [String; 3],
+    daily: Vec<u32>,
+    totals: Vec<u32>,
+}
 
-    let tag = match &user {
-        Some(u) => u.tag(),
-        None => user_id.0.to_string(),
-    };
+// Selects which backend `write_out` uses to serialize `StatsRow`s, read once
+// from `UMS_OUTPUT_FORMAT`. Defaults to CSV so existing behavior is unchanged.
+enum OutputFormat {
+    Csv,
+    Json,
+    Sqlite,
+}
 
-    let pfp = match &user {
-        Some(u) => u.avatar_url().unwrap_or(DEFAULT_PFP.into()),
-        None => DEFAULT_PFP.into(),
-    };
+impl OutputFormat {
+    fn from_env() -> Self {
+        match env::var("UMS_OUTPUT_FORMAT") {
+            Err(_) => OutputFormat::Csv,
+            Ok(v) if v.eq_ignore_ascii_case("csv") => OutputFormat::Csv,
+            Ok(v) if v.eq_ignore_ascii_case("json") => OutputFormat::Json,
+            Ok(v) if v.eq_ignore_ascii_case("sqlite") => OutputFormat::Sqlite,
+            Ok(v) => panic!("Unknown UMS_OUTPUT_FORMAT {v:?} (expected csv, json, or sqlite)"),
+        }
+    }
+}
 
-    let category = match &user {
-        Some(u) => {
-            if u.bot {
-                "Bots"
-            } else {
-                categorize_num(total)
+// Writes the wide one-column-per-day CSV pair this tool has always produced.
+fn write_csv(guild_id: &GuildId, dates: &[String], rows: &[StatsRow]) -> io::Result<Vec<PathBuf>> {
+    let daily_path = DATA_DIR.join(format!("{guild_id}_daily.csv"));
+    let totals_path = DATA_DIR.join(format!("{guild_id}_totals.csv"));
+
+    let mut wtr_daily = csv::Writer::from_writer(File::create(&daily_path)?);
+    let mut wtr_totals = csv::Writer::from_writer(File::create(&totals_path)?);
+
+    let mut header = StringRecord::from(vec!["Username", "Category", "PFP"]);
+    for date in dates {
+        header.push_field(date);
+    }
+    wtr_daily.write_record(&header)?;
+    wtr_totals.write_record(&header)?;
+
+    for row in rows {
+        let daily: Vec<String> = row.daily.iter().map(u32::to_string).collect();
+        let totals: Vec<String> = row.totals.iter().map(u32::to_string).collect();
+
+        wtr_daily.write_record(&[&row.header[..], &daily[..]].concat())?;
+        wtr_totals.write_record(&[&row.header[..], &totals[..]].concat())?;
+    }
+
+    Ok(vec![daily_path, totals_path])
+}
+
+// Writes one line-delimited JSON record per user/webhook, keyed by date.
+fn write_json(guild_id: &GuildId, dates: &[String], rows: &[StatsRow]) -> io::Result<Vec<PathBuf>> {
+    use std::io::Write;
+
+    let path = DATA_DIR.join(format!("{guild_id}_stats.jsonl"));
+    let mut file = File::create(&path)?;
+
+    for row in rows {
+        let daily: HashMap<&String, u32> = dates.iter().zip(row.daily.iter().copied()).collect();
+        let totals: HashMap<&String, u32> = dates.iter().zip(row.totals.iter().copied()).collect();
+
+        let record = serde_json::json!({
+            "id": row.id,
+            "username": row.header[0],
+            "category": row.header[1],
+            "pfp": row.header[2],
+            "daily": daily,
+            "totals": totals,
+        });
+
+        serde_json::to_writer(&file, &record)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        file.write_all(b"\n")?;
+    }
+
+    Ok(vec![path])
+}
+
+// Writes a normalized SQLite database (users/days/daily_counts) so the same
+// data can be queried ad-hoc instead of read from a wide CSV.
+fn write_sqlite(guild_id: &GuildId, dates: &[String], rows: &[StatsRow]) -> io::Result<Vec<PathBuf>> {
+    let path = DATA_DIR.join(format!("{guild_id}.sqlite3"));
+    if path.exists() {
+        fs::remove_file(&path)?;
+    }
+
+    let sqlite_err = |e: rusqlite::Error| io::Error::new(io::ErrorKind::Other, e);
+
+    let mut conn = rusqlite::Connection::open(&path).map_err(sqlite_err)?;
+    conn.execute_batch(
+        "CREATE TABLE users (
+            id INTEGER PRIMARY KEY,
+            discord_id INTEGER,
+            username TEXT NOT NULL,
+            category TEXT NOT NULL,
+            pfp TEXT NOT NULL
+        );
+        CREATE TABLE days (uday INTEGER PRIMARY KEY, date TEXT NOT NULL);
+        CREATE TABLE daily_counts (
+            user_id INTEGER NOT NULL REFERENCES users(id),
+            uday INTEGER NOT NULL REFERENCES days(uday),
+            count INTEGER NOT NULL,
+            PRIMARY KEY (user_id, uday)
+        );",
+    )
+    .map_err(sqlite_err)?;
+
+    let tx = conn.transaction().map_err(sqlite_err)?;
+    {
+        let mut insert_day = tx
+            .prepare("INSERT INTO days (uday, date) VALUES (?1, ?2)")
+            .map_err(sqlite_err)?;
+        for (uday, date) in dates.iter().enumerate() {
+            insert_day
+                .execute(rusqlite::params![uday as i64, date])
+                .map_err(sqlite_err)?;
+        }
+
+        let mut insert_user = tx
+            .prepare("INSERT INTO users (id, discord_id, username, category, pfp) VALUES (?1, ?2, ?3, ?4, ?5)")
+            .map_err(sqlite_err)?;
+        let mut insert_count = tx
+            .prepare("INSERT INTO daily_counts (user_id, uday, count) VALUES (?1, ?2, ?3)")
+            .map_err(sqlite_err)?;
+
+        for (row_id, row) in rows.iter().enumerate() {
+            insert_user
+                .execute(rusqlite::params![
+                    row_id as i64,
+                    row.id.map(|id| id as i64),
+                    row.header[0],
+                    row.header[1],
+                    row.header[2],
+                ])
+                .map_err(sqlite_err)?;
+
+            for (uday, count) in row.daily.iter().enumerate() {
+                insert_count
+                    .execute(rusqlite::params![row_id as i64, uday as i64, count])
+                    .map_err(sqlite_err)?;
+            }
+        }
+    }
+    tx.commit().map_err(sqlite_err)?;
+
+    Ok(vec![path])
+}
+
+// Resolves every given user ID against the cache first, then fetches the
+// cache misses in bounded, limited-concurrency chunks. IDs that cannot be
+// resolved (e.g. deleted accounts) map to `None` so a failing ID is looked
+// up at most once.
+async fn resolve_users(
+    user_ids: impl Iterator<Item = u64>,
+    con: &Context,
+) -> HashMap<u64, Option<ResolvedUser>> {
+    let mut resolved = HashMap::new();
+    let mut misses = Vec::new();
+
+    for id in user_ids {
+        match con.cache.user(id) {
+            Some(u) => {
+                resolved.insert(id, Some(ResolvedUser::from(&u)));
             }
+            None => misses.push(id),
         }
-        None => categorize_num(total),
+    }
+
+    for chunk in misses.chunks(USER_RESOLVE_CHUNK_SIZE) {
+        let fetched: Vec<(u64, Option<ResolvedUser>)> = stream::iter(chunk.iter().copied())
+            .map(|id| async move {
+                let user = UserId(id).to_user(con).await.ok();
+                (id, user.as_ref().map(ResolvedUser::from))
+            })
+            .buffer_unordered(USER_RESOLVE_CHUNK_SIZE)
+            .collect()
+            .await;
+
+        resolved.extend(fetched);
+    }
+
+    resolved
+}
+
+// Resolves the display name to use for `user_id`: the resolved user's tag,
+// falling back to a name recovered from an archive import, falling back to
+// the bare numeric ID.
+fn resolve_tag(
+    user_id: u64,
+    resolved: Option<&ResolvedUser>,
+    known_names: &HashMap<u64, String>,
+) -> String {
+    match resolved {
+        Some(u) => u.tag.clone(),
+        None => known_names
+            .get(&user_id)
+            .cloned()
+            .unwrap_or_else(|| user_id.to_string()),
+    }
+}
+
+fn generate_user_header(
+    user_id: u64,
+    resolved: Option<&ResolvedUser>,
+    total: u32,
+    known_names: &HashMap<u64, String>,
+) -> [String; 3] {
+    let tag = resolve_tag(user_id, resolved, known_names);
+
+    let pfp = match resolved {
+        Some(u) => u.pfp.clone(),
+        None => DEFAULT_PFP.into(),
+    };
+
+    let category = match resolved {
+        Some(u) if u.bot => "Bots",
+        _ => categorize_num(total),
     };
 
     [tag, category.into(), pfp]
@@ -252,13 +836,32 @@ fn categorize_num(n: u32) -> &'static str {
 }
 
 fn timestamp_to_uday(ts: &Timestamp) -> u16 {
-    (ts.unix_timestamp() / 60 / 60 / 24)
+    unix_to_uday(ts.unix_timestamp())
+}
+
+const SECONDS_PER_DAY: i64 = 60 * 60 * 24;
+
+fn unix_to_uday(unix_timestamp: i64) -> u16 {
+    let offset_seconds = i64::from(LOCAL_OFFSET.whole_seconds());
+    let local_timestamp = unix_timestamp
+        .checked_add(offset_seconds)
+        .expect("Timestamp overflowed while applying the local timezone offset!");
+
+    local_timestamp
+        .div_euclid(SECONDS_PER_DAY)
         .try_into()
         .expect("Unexpectedly large timestamp!")
 }
 
 fn uday_to_date(uday: u16) -> String {
-    let ts = time::OffsetDateTime::from_unix_timestamp((uday as i64) * 60 * 60 * 24).unwrap();
-    ts.format(format_description!("[year]-[month]-[day]"))
+    let offset_seconds = i64::from(LOCAL_OFFSET.whole_seconds());
+    let utc_timestamp = (i64::from(uday) * SECONDS_PER_DAY)
+        .checked_sub(offset_seconds)
+        .expect("uday overflowed while removing the local timezone offset!");
+
+    time::OffsetDateTime::from_unix_timestamp(utc_timestamp)
+        .unwrap()
+        .to_offset(*LOCAL_OFFSET)
+        .format(format_description!("[year]-[month]-[day]"))
         .unwrap()
 }
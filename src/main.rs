@@ -1,16 +1,42 @@
 mod datastore;
 
+use std::collections::HashSet;
 use std::env;
+use std::path::{Path, PathBuf};
 
 use dialoguer::Select;
 use serenity::async_trait;
 use serenity::model::gateway::{GatewayIntents, Ready};
-use serenity::model::prelude::{Activity, Guild, GuildChannel};
+use serenity::model::prelude::{Activity, Guild, GuildChannel, GuildId, RoleId, UserId};
 use serenity::prelude::*;
 use std::sync::mpsc;
 
 use crate::datastore::Datastore;
 
+// Imports an offline message archive into the cache for `IMPORT_GUILD` without
+// ever connecting to Discord. Used when a user already has an export for a
+// guild/channel the live crawl can no longer reach (e.g. deleted channels).
+fn import_archive(archive_path: &str) {
+    let guild_id: u64 = env::var("IMPORT_GUILD")
+        .expect("IMPORT_GUILD must be set to the guild ID this archive belongs to")
+        .parse()
+        .expect("IMPORT_GUILD must be a numeric guild ID");
+    let guild_id = GuildId(guild_id);
+
+    let mut datastore = Datastore::load_from_cache(&guild_id).unwrap_or_default();
+
+    let users_file = env::var("IMPORT_USERS_FILE").ok().map(PathBuf::from);
+    datastore
+        .import_archive(Path::new(archive_path), users_file.as_deref())
+        .expect("Unable to import archive");
+
+    datastore
+        .save_to_cache(&guild_id)
+        .expect("Unable to write DS file to cache!");
+
+    println!("Imported archive into cache for guild {guild_id}.");
+}
+
 struct Handler;
 
 // Given a Context, present a menu to the user to select a guild and return it.
@@ -53,6 +79,49 @@ impl EventHandler for Handler {
             Datastore::load_from_cache(guild_id).unwrap_or_default()
         };
 
+        // When `RESTRICT_ROLE` is set, only count messages from members who
+        // currently hold that role. `process_message` can't check this itself:
+        // historic message fetches don't carry member/role data, only live
+        // gateway events do, so the allow-list is resolved once up front.
+        const MEMBER_PAGE_SIZE: u64 = 1000;
+
+        let allowed_authors: Option<HashSet<UserId>> = match env::var("RESTRICT_ROLE") {
+            Ok(role) => {
+                let role_id = RoleId(
+                    role.parse()
+                        .expect("RESTRICT_ROLE must be a numeric role ID"),
+                );
+
+                // Page through every member with the `after` cursor; a single
+                // call only ever returns up to MEMBER_PAGE_SIZE members, which
+                // would otherwise silently drop role holders past the first page.
+                let mut allowed = HashSet::new();
+                let mut after: Option<UserId> = None;
+                loop {
+                    let page = guild_id
+                        .members(&con.http, Some(MEMBER_PAGE_SIZE), after)
+                        .await
+                        .expect("Unable to fetch guild members");
+
+                    allowed.extend(
+                        page.iter()
+                            .filter(|m| m.roles.contains(&role_id))
+                            .map(|m| m.user.id),
+                    );
+
+                    let page_len = page.len() as u64;
+                    after = page.last().map(|m| m.user.id);
+
+                    if page_len < MEMBER_PAGE_SIZE {
+                        break;
+                    }
+                }
+
+                Some(allowed)
+            }
+            Err(_) => None,
+        };
+
         // Get a list of all the channels AND threads.
         let mut chans: Vec<GuildChannel> = guild
             .channels
@@ -132,6 +201,12 @@ impl EventHandler for Handler {
 
                 // Process all the messages in this chunk.
                 for i in messages {
+                    if let Some(allowed) = &allowed_authors {
+                        if !allowed.contains(&i.author.id) {
+                            continue;
+                        }
+                    }
+
                     datastore.process_message(&i);
                 }
 
@@ -174,6 +249,12 @@ impl EventHandler for Handler {
 
 #[tokio::main]
 async fn main() {
+    // Offline ingestion mode: skip the Discord connection entirely.
+    if let Ok(archive_path) = env::var("IMPORT_ARCHIVE") {
+        import_archive(&archive_path);
+        return;
+    }
+
     let token: String = if cfg!(feature = "builtin-token") {
         const TOKEN: &str = "";
 
@@ -182,8 +263,10 @@ async fn main() {
         env::var("DISCORD_TOKEN").expect("No token provided!")
     };
 
-    let intents =
-        GatewayIntents::GUILDS | GatewayIntents::GUILD_MESSAGES | GatewayIntents::MESSAGE_CONTENT;
+    let intents = GatewayIntents::GUILDS
+        | GatewayIntents::GUILD_MESSAGES
+        | GatewayIntents::MESSAGE_CONTENT
+        | GatewayIntents::GUILD_MEMBERS;
 
     let mut client = Client::builder(token, intents)
         .event_handler(Handler)